@@ -0,0 +1,68 @@
+use axum::body::BoxBody;
+use axum::http::{Request, Response};
+use axum::response::IntoResponse;
+use subtle::ConstantTimeEq;
+use tower_http::auth::{AuthorizeRequest, RequireAuthorizationLayer};
+
+use crate::errors::AppError;
+
+/// Checks every request for `Authorization: Bearer <api_key>`, matching it
+/// against the configured `Config.global.api_key`.
+///
+/// Wrap the mutating/admin sub-`Router` with
+/// `RequireAuthorizationLayer::custom(BearerAuth::new(token))` so the check
+/// runs before `handle` and composes with the rest of the layer stack.
+#[derive(Clone)]
+pub struct BearerAuth {
+    token: String,
+}
+
+impl BearerAuth {
+    pub fn new(token: impl Into<String>) -> Self {
+        BearerAuth {
+            token: token.into(),
+        }
+    }
+
+    pub fn layer(token: impl Into<String>) -> RequireAuthorizationLayer<BearerAuth> {
+        RequireAuthorizationLayer::custom(BearerAuth::new(token))
+    }
+}
+
+impl<B> AuthorizeRequest<B> for BearerAuth {
+    type ResponseBody = BoxBody;
+
+    fn authorize(&mut self, request: &mut Request<B>) -> Result<(), Response<Self::ResponseBody>> {
+        let presented = request
+            .headers()
+            .get(http::header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+
+        match presented {
+            Some(token) if tokens_match(token, &self.token) => Ok(()),
+            _ => Err(AppError::Unauthorized.into_response()),
+        }
+    }
+}
+
+/// Constant-time token comparison so a mismatching `Authorization` header
+/// can't be used to brute-force the configured `api_key` byte-by-byte via
+/// response timing.
+fn tokens_match(presented: &str, expected: &str) -> bool {
+    presented.len() == expected.len()
+        && bool::from(presented.as_bytes().ct_eq(expected.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_match_requires_exact_equality() {
+        assert!(tokens_match("secret-token", "secret-token"));
+        assert!(!tokens_match("secret-token", "wrong-token"));
+        assert!(!tokens_match("short", "longer-token"));
+        assert!(!tokens_match("", "secret-token"));
+    }
+}
@@ -3,6 +3,8 @@ use serde::Deserialize;
 #[derive(Deserialize)]
 pub struct Config {
     pub global: Global,
+    #[serde(default)]
+    pub compression: Compression,
 }
 
 #[derive(Deserialize)]
@@ -10,4 +12,29 @@ pub struct Global {
     pub address: String,
     pub db_url: String,
     pub pool_size: usize,
+    pub api_key: String,
+    pub max_body_bytes: u64,
+    pub max_upload_body_bytes: u64,
+    pub max_deploy_archive_bytes: u64,
+}
+
+#[derive(Deserialize)]
+pub struct Compression {
+    pub enabled: bool,
+    pub min_size_bytes: u16,
+    pub gzip: bool,
+    pub brotli: bool,
+    pub deflate: bool,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression {
+            enabled: true,
+            min_size_bytes: 32,
+            gzip: true,
+            brotli: true,
+            deflate: true,
+        }
+    }
 }
\ No newline at end of file
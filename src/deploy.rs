@@ -0,0 +1,285 @@
+use std::io::Cursor;
+use std::path::{Component, Path};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use async_compression::tokio::bufread::GzipDecoder;
+use axum::extract::{BodyStream, Extension};
+use axum::http::StatusCode;
+use futures::TryStreamExt;
+use tokio::io::{AsyncRead, AsyncReadExt, BufReader, ReadBuf};
+use tokio_util::io::StreamReader;
+
+use crate::errors::AppError;
+
+const PUBLISH_LINK: &str = "./publish";
+const RELEASES_DIR: &str = "./publish-releases";
+
+/// Ceilings for a single `/deploy` upload, read from `Config.global`.
+///
+/// `map_request`'s `Content-Length` check only catches declared sizes, and
+/// does nothing for chunked-transfer uploads with no `Content-Length` at
+/// all; `max_compressed_bytes` is enforced against the bytes actually read
+/// off the wire via [`LimitedReader`]. `max_decompressed_bytes` guards the
+/// output of gzip decoding separately, since a small compressed body can
+/// still expand into a gzip bomb.
+#[derive(Clone, Copy)]
+pub struct DeployLimits {
+    pub max_compressed_bytes: u64,
+    pub max_decompressed_bytes: u64,
+}
+
+/// `POST /deploy` — streams a gzip-compressed tar archive and atomically
+/// swaps it in as the new `./publish` static site.
+///
+/// The body is decoded as it arrives rather than buffered up front; only the
+/// decompressed tar bytes are held in memory before unpacking, since the
+/// `tar` crate only reads synchronously, and that read is itself capped by
+/// `DeployLimits` so a gzip bomb can't exhaust memory. Entries are unpacked
+/// into a fresh release directory under `./publish-releases` first and
+/// `./publish` is only repointed at it, via [`swap_publish_symlink`], once
+/// the whole archive has been validated, so a failed or malicious upload
+/// never leaves `./publish` half-written. The one exception to `./publish`
+/// always existing is the first deploy over a plain `./publish` directory,
+/// which is moved aside before the symlink replacing it is created.
+pub async fn deploy(
+    body: BodyStream,
+    Extension(limits): Extension<DeployLimits>,
+) -> Result<StatusCode, AppError> {
+    let stream = body.map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err));
+    let limited = LimitedReader::new(StreamReader::new(stream), limits.max_compressed_bytes);
+    let decoder = GzipDecoder::new(BufReader::new(limited));
+
+    let archive_bytes = read_decompressed_within_limit(decoder, limits.max_decompressed_bytes).await?;
+
+    tokio::task::spawn_blocking(move || unpack_and_swap(archive_bytes))
+        .await
+        .map_err(|err| {
+            tracing::debug!("{:?}", err);
+            AppError::Internal("deploy task panicked".to_string())
+        })?
+        .map(|_| StatusCode::OK)
+}
+
+/// Marker error so callers can tell "the compressed body was too large"
+/// apart from a genuinely malformed stream without leaking detail text.
+#[derive(Debug)]
+struct CompressedBodyTooLarge;
+
+impl std::fmt::Display for CompressedBodyTooLarge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "compressed request body exceeds the configured limit")
+    }
+}
+
+impl std::error::Error for CompressedBodyTooLarge {}
+
+/// Wraps an [`AsyncRead`] and errors out once more than `limit` bytes have
+/// been read through it, so an unbounded chunked-transfer upload with no
+/// `Content-Length` can't be used to bypass `map_request`'s size check.
+struct LimitedReader<R> {
+    inner: R,
+    remaining: u64,
+}
+
+impl<R> LimitedReader<R> {
+    fn new(inner: R, limit: u64) -> Self {
+        LimitedReader {
+            inner,
+            remaining: limit,
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for LimitedReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let filled_before = buf.filled().len();
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if poll.is_ready() {
+            let read = (buf.filled().len() - filled_before) as u64;
+            if read > self.remaining {
+                return Poll::Ready(Err(std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    CompressedBodyTooLarge,
+                )));
+            }
+            self.remaining -= read;
+        }
+        poll
+    }
+}
+
+/// Reads `reader` to completion, bailing out with
+/// [`AppError::PayloadTooLarge`] as soon as the decompressed byte count
+/// crosses `limit` instead of growing the buffer without bound.
+async fn read_decompressed_within_limit<R>(mut reader: R, limit: u64) -> Result<Vec<u8>, AppError>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut archive_bytes = Vec::new();
+    let mut chunk = [0u8; 64 * 1024];
+    loop {
+        let n = reader.read(&mut chunk).await.map_err(|err| {
+            if err
+                .get_ref()
+                .map_or(false, |inner| inner.is::<CompressedBodyTooLarge>())
+            {
+                return AppError::PayloadTooLarge;
+            }
+            tracing::debug!("{:?}", err);
+            AppError::BadBody("bad gzip stream".to_string())
+        })?;
+        if n == 0 {
+            break;
+        }
+        if archive_bytes.len() as u64 + n as u64 > limit {
+            return Err(AppError::PayloadTooLarge);
+        }
+        archive_bytes.extend_from_slice(&chunk[..n]);
+    }
+    Ok(archive_bytes)
+}
+
+fn unpack_and_swap(archive_bytes: Vec<u8>) -> Result<(), AppError> {
+    let publish_link = Path::new(PUBLISH_LINK);
+    let releases_dir = Path::new(RELEASES_DIR);
+    std::fs::create_dir_all(releases_dir).map_err(internal_io_error)?;
+
+    let release_dir = tempfile::Builder::new()
+        .prefix("release-")
+        .tempdir_in(releases_dir)
+        .map_err(internal_io_error)?;
+
+    let mut archive = tar::Archive::new(Cursor::new(archive_bytes));
+    for entry in archive.entries().map_err(bad_archive)? {
+        let mut entry = entry.map_err(bad_archive)?;
+        let path = entry.path().map_err(bad_archive)?.into_owned();
+        if !is_safe_entry_path(&path) {
+            return Err(AppError::BadBody(format!(
+                "archive entry escapes destination root: {}",
+                path.display()
+            )));
+        }
+        entry.unpack_in(release_dir.path()).map_err(internal_io_error)?;
+    }
+
+    let release_path = release_dir.into_path();
+    swap_publish_symlink(publish_link, &release_path).map_err(|err| {
+        let _ = std::fs::remove_dir_all(&release_path);
+        err
+    })
+}
+
+/// Repoints `publish_link` at `release_path` by creating a new symlink next
+/// to it and renaming that symlink on top, rather than renaming directories
+/// in and out of place.
+///
+/// A directory-rename swap (the previous design) always has a window where
+/// `publish_link` doesn't exist at all — between moving the old directory
+/// aside and moving the new one in — and a crash in that window leaves the
+/// site down with nothing to roll back to. Renaming a symlink onto
+/// `publish_link`'s path is a single filesystem operation, so `publish_link`
+/// always resolves to either the previous release or the new one, never
+/// neither, once it is a symlink; when it is still a plain directory it is
+/// first renamed into `RELEASES_DIR`, and it is missing until the symlink
+/// rename below completes. Old releases under `RELEASES_DIR` are left on disk rather than
+/// deleted, so a crash before the final rename just leaves the symlink
+/// pointing at the last-known-good release.
+fn swap_publish_symlink(publish_link: &Path, release_path: &Path) -> Result<(), AppError> {
+    let is_plain_dir = std::fs::symlink_metadata(publish_link)
+        .map(|meta| meta.file_type().is_dir())
+        .unwrap_or(false);
+    if is_plain_dir {
+        // First deploy against a tree where `./publish` predates this
+        // symlink scheme (e.g. a static checkout). Move it into
+        // `RELEASES_DIR` so it becomes an ordinary release and
+        // `publish_link` is free to become a symlink.
+        let pre_existing = release_path
+            .parent()
+            .unwrap_or_else(|| Path::new("."))
+            .join("pre-existing");
+        if !pre_existing.exists() {
+            std::fs::rename(publish_link, &pre_existing).map_err(internal_io_error)?;
+        }
+    }
+
+    let tmp_link = publish_link.with_extension("new-link");
+    let _ = std::fs::remove_file(&tmp_link);
+    std::os::unix::fs::symlink(release_path, &tmp_link).map_err(internal_io_error)?;
+    std::fs::rename(&tmp_link, publish_link).map_err(internal_io_error)?;
+    Ok(())
+}
+
+/// Rejects archive entries whose normalized path would climb out of the
+/// destination root via `..` components, an absolute path, or a prefix.
+fn is_safe_entry_path(path: &Path) -> bool {
+    !path.components().any(|component| {
+        matches!(
+            component,
+            Component::ParentDir | Component::RootDir | Component::Prefix(_)
+        )
+    })
+}
+
+fn bad_archive(err: std::io::Error) -> AppError {
+    tracing::debug!("{:?}", err);
+    AppError::BadBody("malformed archive".to_string())
+}
+
+fn internal_io_error(err: std::io::Error) -> AppError {
+    tracing::debug!("{:?}", err);
+    AppError::Internal("deploy io error".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn is_safe_entry_path_rejects_traversal_and_absolute_paths() {
+        assert!(is_safe_entry_path(&PathBuf::from("index.html")));
+        assert!(is_safe_entry_path(&PathBuf::from("assets/app.js")));
+        assert!(!is_safe_entry_path(&PathBuf::from("../etc/passwd")));
+        assert!(!is_safe_entry_path(&PathBuf::from("assets/../../secret")));
+        assert!(!is_safe_entry_path(&PathBuf::from("/etc/passwd")));
+    }
+
+    #[tokio::test]
+    async fn read_decompressed_within_limit_rejects_gzip_bomb_style_stream() {
+        let oversized = std::io::Cursor::new(vec![0u8; 1024]);
+        let err = read_decompressed_within_limit(oversized, 16)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AppError::PayloadTooLarge));
+    }
+
+    #[tokio::test]
+    async fn read_decompressed_within_limit_accepts_data_under_the_cap() {
+        let data = std::io::Cursor::new(vec![1u8; 8]);
+        let bytes = read_decompressed_within_limit(data, 16).await.unwrap();
+        assert_eq!(bytes.len(), 8);
+    }
+
+    #[tokio::test]
+    async fn limited_reader_rejects_streams_over_the_compressed_cap() {
+        let data = std::io::Cursor::new(vec![0u8; 1024]);
+        let mut limited = LimitedReader::new(data, 16);
+        let mut buf = [0u8; 1024];
+        let err = limited.read(&mut buf).await.unwrap_err();
+        assert!(err.get_ref().unwrap().is::<CompressedBodyTooLarge>());
+    }
+
+    #[tokio::test]
+    async fn limited_reader_accepts_streams_under_the_compressed_cap() {
+        let data = std::io::Cursor::new(vec![0u8; 8]);
+        let mut limited = LimitedReader::new(data, 16);
+        let mut buf = [0u8; 1024];
+        let n = limited.read(&mut buf).await.unwrap();
+        assert_eq!(n, 8);
+    }
+}
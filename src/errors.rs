@@ -0,0 +1,119 @@
+use std::fmt;
+
+use axum::body::{box_body, BoxBody};
+use axum::http::{Response, StatusCode};
+use axum::response::IntoResponse;
+use axum::Json;
+use serde::Serialize;
+
+/// Single error type for the whole app.
+///
+/// Handlers and middleware return this (or `?`-propagate into it) instead of
+/// hand-building `(StatusCode, String)` tuples, so every error path renders
+/// the same `{ "error": ..., "code": ... }` JSON body with the right status.
+#[derive(Debug)]
+pub enum AppError {
+    NotFound,
+    Unauthorized,
+    Timeout,
+    Database(String),
+    BadBody(String),
+    PayloadTooLarge,
+    Internal(String),
+}
+
+impl AppError {
+    fn status(&self) -> StatusCode {
+        match self {
+            AppError::NotFound => StatusCode::NOT_FOUND,
+            AppError::Unauthorized => StatusCode::UNAUTHORIZED,
+            AppError::Timeout => StatusCode::REQUEST_TIMEOUT,
+            AppError::Database(_) => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::BadBody(_) => StatusCode::BAD_REQUEST,
+            AppError::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn code(&self) -> &'static str {
+        match self {
+            AppError::NotFound => "NOT_FOUND",
+            AppError::Unauthorized => "UNAUTHORIZED",
+            AppError::Timeout => "TIMEOUT",
+            AppError::Database(_) => "DATABASE_ERROR",
+            AppError::BadBody(_) => "BAD_BODY",
+            AppError::PayloadTooLarge => "PAYLOAD_TOO_LARGE",
+            AppError::Internal(_) => "INTERNAL_ERROR",
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::NotFound => write!(f, "not found"),
+            AppError::Unauthorized => write!(f, "unauthorized"),
+            AppError::Timeout => write!(f, "request took too long"),
+            AppError::Database(msg) | AppError::BadBody(msg) | AppError::Internal(msg) => {
+                write!(f, "{}", msg)
+            }
+            AppError::PayloadTooLarge => write!(f, "request body exceeds the configured limit"),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    error: String,
+    code: &'static str,
+}
+
+impl IntoResponse for AppError {
+    type Body = BoxBody;
+    type BodyError = std::convert::Infallible;
+
+    fn into_response(self) -> Response<Self::Body> {
+        let status = self.status();
+        let code = self.code();
+        let error = self.to_string();
+        let mut response = Json(ErrorBody { error, code }).into_response().map(box_body);
+        *response.status_mut() = status;
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_variant_maps_to_its_documented_status() {
+        assert_eq!(AppError::NotFound.status(), StatusCode::NOT_FOUND);
+        assert_eq!(AppError::Unauthorized.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(AppError::Timeout.status(), StatusCode::REQUEST_TIMEOUT);
+        assert_eq!(
+            AppError::Database("x".to_string()).status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        assert_eq!(
+            AppError::BadBody("x".to_string()).status(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            AppError::PayloadTooLarge.status(),
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+        assert_eq!(
+            AppError::Internal("x".to_string()).status(),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+
+    #[test]
+    fn into_response_carries_the_variant_status_not_just_200() {
+        let response = AppError::Unauthorized.into_response();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+}
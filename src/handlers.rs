@@ -0,0 +1,90 @@
+use axum::extract::Extension;
+use axum::Json;
+use deadpool_postgres::{Manager, ManagerConfig, Pool, RecyclingMethod};
+use serde::Serialize;
+use tokio_postgres::NoTls;
+
+use crate::errors::AppError;
+
+/// Builds the shared connection pool from `Config.global.db_url` /
+/// `pool_size`. Checkout and query failures surface as HTTP errors from the
+/// handlers below rather than panicking the server.
+pub fn build_pool(db_url: &str, pool_size: usize) -> Pool {
+    let pg_config = db_url
+        .parse::<tokio_postgres::Config>()
+        .expect("invalid db_url");
+    let manager_config = ManagerConfig {
+        recycling_method: RecyclingMethod::Fast,
+    };
+    let manager = Manager::from_config(pg_config, NoTls, manager_config);
+    Pool::builder(manager)
+        .max_size(pool_size)
+        .build()
+        .expect("failed to build db pool")
+}
+
+#[derive(Serialize)]
+pub struct User {
+    id: i32,
+    username: String,
+}
+
+#[derive(Serialize)]
+pub struct Product {
+    id: i32,
+    name: String,
+}
+
+/// `GET /health` — checks out a connection and runs `SELECT 1` so load
+/// balancers can detect a dead pool before routing real traffic to it.
+pub async fn health(Extension(pool): Extension<Pool>) -> Result<&'static str, AppError> {
+    let client = pool.get().await.map_err(pool_error)?;
+    client.query_one("SELECT 1", &[]).await.map_err(query_error)?;
+    Ok("ok")
+}
+
+pub async fn user_list(
+    Extension(pool): Extension<Pool>,
+) -> Result<Json<Vec<User>>, AppError> {
+    let client = pool.get().await.map_err(pool_error)?;
+    let rows = client
+        .query("SELECT id, username FROM users ORDER BY id", &[])
+        .await
+        .map_err(query_error)?;
+    let users = rows
+        .iter()
+        .map(|row| User {
+            id: row.get("id"),
+            username: row.get("username"),
+        })
+        .collect();
+    Ok(Json(users))
+}
+
+pub async fn product_list(
+    Extension(pool): Extension<Pool>,
+) -> Result<Json<Vec<Product>>, AppError> {
+    let client = pool.get().await.map_err(pool_error)?;
+    let rows = client
+        .query("SELECT id, name FROM products ORDER BY id", &[])
+        .await
+        .map_err(query_error)?;
+    let products = rows
+        .iter()
+        .map(|row| Product {
+            id: row.get("id"),
+            name: row.get("name"),
+        })
+        .collect();
+    Ok(Json(products))
+}
+
+fn pool_error(err: deadpool_postgres::PoolError) -> AppError {
+    tracing::debug!("{:?}", err);
+    AppError::Database("database pool checkout failed".to_string())
+}
+
+fn query_error(err: tokio_postgres::Error) -> AppError {
+    tracing::debug!("{:?}", err);
+    AppError::Database("database query failed".to_string())
+}
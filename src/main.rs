@@ -1,8 +1,8 @@
 use axum::body::{box_body, Body, BoxBody, Bytes};
-use axum::http::{Response, StatusCode};
-use deadpool_postgres::Pool;
+use axum::http::Response;
 use figment::providers::{Format, Toml};
 use figment::Figment;
+use futures::future::poll_fn;
 use http::Request;
 use std::convert::Infallible;
 use std::net::SocketAddr;
@@ -10,14 +10,24 @@ use std::str::FromStr;
 use std::time::Duration;
 use tower::util::MapResponseLayer;
 use tower::{filter::AsyncFilterLayer, util::AndThenLayer, BoxError, ServiceBuilder};
+use tower_http::compression::{
+    predicate::{Predicate, SizeAbove},
+    CompressionLayer,
+};
 use tower_http::services::ServeDir;
 use tower_http::trace::TraceLayer;
 use tracing::debug;
 
+use crate::auth::BearerAuth;
 use crate::config::Config;
+use crate::errors::AppError;
 use axum::{handler::get, handler::post, handler::Handler, AddExtensionLayer, Router};
 
+mod auth;
 mod config;
+mod deploy;
+mod errors;
+mod handlers;
 
 #[tokio::main]
 async fn main() {
@@ -35,32 +45,94 @@ async fn main() {
         .extract()
         .unwrap();
 
-    let app = Router::new()
+    let pool = handlers::build_pool(&config.global.db_url, config.global.pool_size);
+
+    let body_limits = BodyLimits {
+        default_bytes: config.global.max_body_bytes,
+        upload_bytes: config.global.max_upload_body_bytes,
+    };
+
+    let app = app_routes(&config)
+        .layer(AsyncFilterLayer::new(move |req: Request<Body>| {
+            map_request(req, body_limits)
+        }))
+        .layer(AndThenLayer::new(map_response))
+        .layer(
+            ServiceBuilder::new()
+                .timeout(Duration::from_secs(15))
+                .layer(TraceLayer::new_for_http())
+                .layer(compression_layer(&config.compression))
+                .into_inner(),
+        )
+        .layer(AddExtensionLayer::new(pool))
+        .handle_error(|error: BoxError| {
+            let error = match error.downcast::<AppError>() {
+                Ok(app_error) => *app_error,
+                Err(error) if error.is::<tower::timeout::error::Elapsed>() => AppError::Timeout,
+                Err(error) => {
+                    tracing::debug!("{:?}", error);
+                    AppError::Internal("unhandled internal error".to_string())
+                }
+            };
+            Ok::<_, Infallible>(error)
+        });
+    // .check_infallible();
+    let addr: SocketAddr = config.global.address.parse::<SocketAddr>().unwrap();
+    debug!("listening on {}", addr);
+    axum::Server::bind(&addr)
+        .serve(app.into_make_service())
+        .await
+        .unwrap();
+}
+
+/// Builds the whole route table: the bearer-gated mutating/admin routes
+/// (`protected`) merged with the public static site and read-only routes
+/// (`public`), falling back to `map_404`.
+///
+/// `protected` MUST come first: axum's `Router::or` only falls through to
+/// its second half when the first half has no matching route at all, and
+/// `public` nests a catch-all `ServeDir` at `"/"` that structurally matches
+/// every path. Putting `public` first would let that root nest swallow
+/// every request before `BearerAuth` (or any protected handler) ever runs.
+fn app_routes(
+    config: &Config,
+) -> impl axum::service::Service<
+    http::Request<Body>,
+    Response = http::Response<BoxBody>,
+    Error = std::convert::Infallible,
+> + Clone {
+    let protected = Router::new()
         .nest(
-            "/",
-            axum::service::get(ServeDir::new("./publish").append_index_html_on_directories(true))
+            "/upload",
+            axum::service::get(ServeDir::new("./upload").append_index_html_on_directories(false))
                 .handle_error(|error: std::io::Error| {
-                    Ok::<_, std::convert::Infallible>((
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        format!("Unhandled internal error: {}", error),
-                    ))
+                    tracing::debug!("{:?}", error);
+                    Ok::<_, Infallible>(AppError::Internal("read file error".to_string()))
                 }),
         )
+        .route("/upload/image", post(handle))
+        .route("/product/create", post(handle))
+        .route("/product/delete", post(handle))
+        .route("/deploy", post(deploy::deploy))
+        .layer(AddExtensionLayer::new(deploy::DeployLimits {
+            max_compressed_bytes: config.global.max_upload_body_bytes,
+            max_decompressed_bytes: config.global.max_deploy_archive_bytes,
+        }))
+        .layer(BearerAuth::layer(config.global.api_key.clone()));
+
+    let public = Router::new()
         .nest(
-            "/upload",
-            axum::service::get(ServeDir::new("./upload").append_index_html_on_directories(false))
+            "/",
+            axum::service::get(ServeDir::new("./publish").append_index_html_on_directories(true))
                 .handle_error(|error: std::io::Error| {
                     tracing::debug!("{:?}", error);
-                    Ok::<_, std::convert::Infallible>((
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        "read file error".to_string(),
-                    ))
+                    Ok::<_, Infallible>(AppError::Internal("read file error".to_string()))
                 }),
         )
+        .route("/health", get(handlers::health))
         .route("/pay/pay_params", post(handle))
         .route("/pay/transfer_info", post(handle))
-        .route("/upload/image", post(handle))
-        .route("/user/list", get(handle))
+        .route("/user/list", get(handlers::user_list))
         .route("/user/create", post(handle))
         .route("/user/login", post(handle))
         .route("/user/info", get(handle))
@@ -69,77 +141,234 @@ async fn main() {
         .route("/order/create", post(handle))
         .route("/order/all", get(handle))
         .route("/order/list", get(handle))
-        .route("/product/list", get(handle))
+        .route("/product/list", get(handlers::product_list))
         .route("/product/home", get(handle))
-        .route("/product/create", post(handle))
         .route("/product/update/:id", post(handle))
-        .route("/product/delete", post(handle))
         .route("/product/detail", get(handle))
         .route("/product/earnings/create", post(handle))
         .route("/product/earnings/delete", post(handle))
-        .route("/product/earnings/find", get(handle))
-        .or(map_404.into_service())
-        .layer(AsyncFilterLayer::new(map_request))
-        .layer(AndThenLayer::new(map_response))
-        .layer(
-            ServiceBuilder::new()
-                .timeout(Duration::from_secs(15))
-                .layer(TraceLayer::new_for_http())
-                .into_inner(),
-        )
-        .handle_error(|error: BoxError| {
-            if error.is::<tower::timeout::error::Elapsed>() {
-                Ok::<_, Infallible>((
-                    StatusCode::REQUEST_TIMEOUT,
-                    "request took too long".to_string(),
-                ))
-            } else {
-                tracing::debug!("{:?}", error);
-                Ok::<_, Infallible>((
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "Unhandled internal error".to_string(),
-                ))
-            }
-        });
-    // .check_infallible();
-    let addr: SocketAddr = config.global.address.parse::<SocketAddr>().unwrap();
-    debug!("listening on {}", addr);
-    axum::Server::bind(&addr)
-        .serve(app.into_make_service())
-        .await
-        .unwrap();
+        .route("/product/earnings/find", get(handle));
+
+    protected.or(public).or(map_404.into_service())
 }
 
-async fn map_404() -> &'static str {
-    "not found"
+/// Builds the response compression layer from `[compression]` config.
+///
+/// Compression only kicks in above `min_size_bytes` and only for the
+/// algorithms the client advertises via `Accept-Encoding`; when the section
+/// is disabled the predicate always rejects, leaving the layer a no-op.
+fn compression_layer(cfg: &config::Compression) -> CompressionLayer<impl Predicate> {
+    let predicate = SizeAbove::new(cfg.min_size_bytes).and(CompressionEnabled(cfg.enabled));
+    CompressionLayer::new()
+        .gzip(cfg.gzip)
+        .br(cfg.brotli)
+        .deflate(cfg.deflate)
+        .compress_when(predicate)
 }
 
-async fn map_request(req: Request<Body>) -> Result<Request<Body>, BoxError> {
+#[derive(Clone, Copy)]
+struct CompressionEnabled(bool);
+
+impl Predicate for CompressionEnabled {
+    fn should_compress<B>(&self, _response: &Response<B>) -> bool {
+        self.0
+    }
+}
+
+async fn map_404() -> AppError {
+    AppError::NotFound
+}
+
+/// Per-route request body ceilings, read from `Config.global`.
+///
+/// Upload-ish endpoints (`/upload`, `/deploy`) get the larger limit so image
+/// and site-archive uploads fit; everything else gets the small JSON default.
+#[derive(Clone, Copy)]
+struct BodyLimits {
+    default_bytes: u64,
+    upload_bytes: u64,
+}
+
+impl BodyLimits {
+    fn for_path(&self, path: &str) -> u64 {
+        if path.starts_with("/upload") || path.starts_with("/deploy") {
+            self.upload_bytes
+        } else {
+            self.default_bytes
+        }
+    }
+}
+
+/// Routes whose handler consumes the request body as a stream (currently
+/// just `/deploy`) and must never have it buffered/rebuilt by `map_request`,
+/// or the whole point of their streaming design is lost.
+fn is_streaming_route(path: &str) -> bool {
+    path.starts_with("/deploy")
+}
+
+async fn map_request(req: Request<Body>, limits: BodyLimits) -> Result<Request<Body>, BoxError> {
+    let path = req.uri().path();
+    let limit = limits.for_path(path);
+    let declared_too_big = req
+        .headers()
+        .get(http::header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok())
+        .map_or(false, |content_length| content_length > limit);
+    if declared_too_big {
+        return Err(Box::new(AppError::PayloadTooLarge));
+    }
+
+    if is_streaming_route(path) {
+        return Ok(req);
+    }
+
     let (parts, body) = req.into_parts();
-    let bytes = buffer_and_print("request", body).await?;
+    let bytes = buffer_and_print("request", body, Some(limit)).await?;
     let req = Request::from_parts(parts, Body::from(bytes));
     Ok(req)
 }
 
 async fn map_response(res: Response<BoxBody>) -> Result<Response<Body>, BoxError> {
     let (parts, body) = res.into_parts();
-    let bytes = buffer_and_print("response", body).await?;
+    let bytes = buffer_and_print("response", body, None).await?;
     let res = Response::from_parts(parts, Body::from(bytes));
     Ok(res)
 }
 
-async fn buffer_and_print<B>(direction: &str, body: B) -> Result<Bytes, BoxError>
+async fn buffer_and_print<B>(direction: &str, body: B, limit: Option<u64>) -> Result<Bytes, BoxError>
 where
     B: axum::body::HttpBody<Data = Bytes>,
     B::Error: Into<BoxError>,
 {
-    let bytes = hyper::body::to_bytes(body).await.map_err(Into::into)?;
+    let bytes = match limit {
+        Some(limit) => to_bytes_within_limit(body, limit).await?,
+        None => hyper::body::to_bytes(body).await.map_err(Into::into)?,
+    };
     if let Ok(body) = std::str::from_utf8(&bytes) {
         tracing::debug!("{} body = {:?}", direction, body);
     }
     Ok(bytes)
 }
 
+/// Drains `body` into `Bytes`, bailing out with [`AppError::PayloadTooLarge`]
+/// as soon as the running total crosses `limit` instead of buffering it all first.
+async fn to_bytes_within_limit<B>(body: B, limit: u64) -> Result<Bytes, BoxError>
+where
+    B: axum::body::HttpBody<Data = Bytes>,
+    B::Error: Into<BoxError>,
+{
+    let mut body = Box::pin(body);
+    let mut collected = Vec::new();
+    while let Some(chunk) = poll_fn(|cx| body.as_mut().poll_data(cx)).await {
+        let chunk = chunk.map_err(Into::into)?;
+        if collected.len() as u64 + chunk.len() as u64 > limit {
+            return Err(Box::new(AppError::PayloadTooLarge));
+        }
+        collected.extend_from_slice(&chunk);
+    }
+    Ok(Bytes::from(collected))
+}
+
 async fn handle() -> &'static str {
     return "Hello,World";
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower::ServiceExt;
+
+    fn test_config() -> Config {
+        Config {
+            global: config::Global {
+                address: "127.0.0.1:0".to_string(),
+                db_url: "postgres://localhost/test".to_string(),
+                pool_size: 1,
+                api_key: "secret-token".to_string(),
+                max_body_bytes: 64 * 1024,
+                max_upload_body_bytes: 64 * 1024 * 1024,
+                max_deploy_archive_bytes: 256 * 1024 * 1024,
+            },
+            compression: config::Compression::default(),
+        }
+    }
+
+    fn post(path: &str) -> Request<Body> {
+        Request::post(path).body(Body::empty()).unwrap()
+    }
+
+    fn authorized_post(path: &str) -> Request<Body> {
+        Request::post(path)
+            .header(http::header::AUTHORIZATION, "Bearer secret-token")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn protected_routes_require_bearer_token() {
+        let app = app_routes(&test_config());
+
+        let res = app.clone().oneshot(post("/product/create")).await.unwrap();
+        assert_eq!(res.status(), http::StatusCode::UNAUTHORIZED);
+
+        let res = app
+            .clone()
+            .oneshot(authorized_post("/product/create"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), http::StatusCode::OK);
+    }
+
+    /// Regression test for the `public.or(protected)` ordering bug: a root
+    /// `"/"` static-file nest in `public` must not swallow these paths
+    /// before `BearerAuth` gets a chance to run.
+    #[tokio::test]
+    async fn protected_routes_are_not_swallowed_by_public_root_nest() {
+        let app = app_routes(&test_config());
+
+        for path in ["/upload/image", "/product/create", "/product/delete", "/deploy"] {
+            let res = app.clone().oneshot(post(path)).await.unwrap();
+            assert_eq!(
+                res.status(),
+                http::StatusCode::UNAUTHORIZED,
+                "{} should reach BearerAuth, not the public root nest",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn is_streaming_route_matches_only_deploy() {
+        assert!(is_streaming_route("/deploy"));
+        assert!(!is_streaming_route("/upload/image"));
+        assert!(!is_streaming_route("/product/create"));
+    }
+
+    #[test]
+    fn body_limits_use_upload_ceiling_for_upload_and_deploy() {
+        let limits = BodyLimits {
+            default_bytes: 1024,
+            upload_bytes: 1024 * 1024,
+        };
+        assert_eq!(limits.for_path("/upload/image"), 1024 * 1024);
+        assert_eq!(limits.for_path("/deploy"), 1024 * 1024);
+        assert_eq!(limits.for_path("/user/create"), 1024);
+    }
+
+    #[tokio::test]
+    async fn oversized_json_request_is_rejected_with_413() {
+        let limits = BodyLimits {
+            default_bytes: 8,
+            upload_bytes: 1024,
+        };
+        let req = Request::post("/user/create")
+            .body(Body::from("this body is over the limit"))
+            .unwrap();
+        let err = map_request(req, limits).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<AppError>(),
+            Some(AppError::PayloadTooLarge)
+        ));
+    }
+}